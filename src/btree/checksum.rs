@@ -0,0 +1,18 @@
+/// A small 64-bit hash used to checksum the live bytes of a packed
+/// page. There are no external dependencies worth pulling in for this,
+/// so this is a hand-rolled FNV-1a over the bytes of every part in
+/// order: cheap, dependency-free, and good enough to catch mmap
+/// corruption rather than to resist adversarial collisions.
+pub fn hash_parts(parts: &[&[u8]]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut h = OFFSET;
+    for part in parts {
+        for &b in part.iter() {
+            h ^= b as u64;
+            h = h.wrapping_mul(PRIME);
+        }
+    }
+    h
+}
@@ -0,0 +1,76 @@
+/// An associative, identity-having operation over cached subtree
+/// summaries, parameterizing `MappedBTree` so `fold` can combine whole
+/// covered subtrees in O(log n) instead of visiting every leaf.
+///
+/// `InnerNode` caches one `Summary` per child, recomputed whenever that
+/// child changes; leaves have no cache and fold their `data` on demand
+/// via `lift`/`combine`.
+pub trait Monoid {
+    type Summary: Copy;
+
+    /// The two-sided identity: `combine(identity(), x) == x == combine(x, identity())`.
+    fn identity() -> Self::Summary;
+
+    /// Combines two summaries in key order; must be associative.
+    fn combine(a: Self::Summary, b: Self::Summary) -> Self::Summary;
+
+    /// Lifts a single leaf value into a summary.
+    fn lift(value: u64) -> Self::Summary;
+}
+
+/// Range-sum over `u64` values.
+pub struct Sum;
+
+impl Monoid for Sum {
+    type Summary = u64;
+
+    fn identity() -> u64 {
+        0
+    }
+
+    fn combine(a: u64, b: u64) -> u64 {
+        a.wrapping_add(b)
+    }
+
+    fn lift(value: u64) -> u64 {
+        value
+    }
+}
+
+/// Range-min over `u64` values.
+pub struct Min;
+
+impl Monoid for Min {
+    type Summary = u64;
+
+    fn identity() -> u64 {
+        u64::max_value()
+    }
+
+    fn combine(a: u64, b: u64) -> u64 {
+        ::std::cmp::min(a, b)
+    }
+
+    fn lift(value: u64) -> u64 {
+        value
+    }
+}
+
+/// Range-max over `u64` values.
+pub struct Max;
+
+impl Monoid for Max {
+    type Summary = u64;
+
+    fn identity() -> u64 {
+        0
+    }
+
+    fn combine(a: u64, b: u64) -> u64 {
+        ::std::cmp::max(a, b)
+    }
+
+    fn lift(value: u64) -> u64 {
+        value
+    }
+}
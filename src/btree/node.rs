@@ -1,6 +1,51 @@
-use std::{ptr, mem};
+use std::{ptr, mem, slice};
 use mappedheap::PageId;
 
+use super::checksum;
+use super::monoid::Monoid;
+
+#[cfg(feature = "simd_support")]
+use packed_simd::u64x8;
+
+/// Finds `target` in `keys`, mirroring `[u64]::binary_search`'s
+/// `Ok`/`Err` contract: `Ok(i)` on an exact match at `i`, `Err(i)` for
+/// the insertion point that keeps `keys` sorted.
+#[cfg(not(feature = "simd_support"))]
+fn scan(keys: &[u64], target: u64) -> Result<usize, usize> {
+    keys.binary_search(&target)
+}
+
+/// SIMD linear scan over 8-lane chunks: broadcast `target`, compare
+/// `keys[i] >= target` across a lane, and take the index of the first
+/// set bit in the resulting mask. Lanes beyond `keys.len()` are padded
+/// with `u64::MAX`, which is `>=` any target, so a final chunk whose real
+/// keys are all below `target` reports the first padded lane, i.e.
+/// `keys.len()`, as the insertion point.
+#[cfg(feature = "simd_support")]
+fn scan(keys: &[u64], target: u64) -> Result<usize, usize> {
+    let count = keys.len();
+    let needle = u64x8::splat(target);
+
+    let mut base = 0;
+    while base < count {
+        let mut chunk = [u64::max_value(); 8];
+        let take = ::std::cmp::min(count - base, 8);
+        chunk[..take].copy_from_slice(&keys[base..base + take]);
+
+        let mask = u64x8::from_slice_unaligned(&chunk).ge(needle).bitmask();
+        if mask != 0 {
+            let pos = base + mask.trailing_zeros() as usize;
+            return if pos < count && keys[pos] == target {
+                Ok(pos)
+            } else {
+                Err(pos)
+            };
+        }
+        base += 8;
+    }
+    Err(count)
+}
+
 pub trait Node<T> : Sized {
     #[cfg(test)]
     fn debug(&self);
@@ -8,6 +53,16 @@ pub trait Node<T> : Sized {
     fn content(&self) -> &[T];
     fn content_mut(&mut self) -> &mut [T];
     fn count(&self) -> usize;
+    /// Recomputes and stores the page checksum over the node's live
+    /// bytes. Must be called after any mutation.
+    fn recompute_checksum(&mut self);
+    /// Checks the stored checksum against the live bytes.
+    fn checksum_valid(&self) -> bool;
+    /// The id of the write transaction that last stamped this page, used
+    /// by the copy-on-write path to decide whether a page can be
+    /// mutated in place or must be cloned first.
+    fn txid(&self) -> u64;
+    fn set_txid(&mut self, txid: u64);
     fn half_full(&self) -> bool {
         self.count() <= 127
     }
@@ -33,11 +88,10 @@ pub trait Node<T> : Sized {
     fn remove_idx(&mut self, key: usize) -> (u64, T);
     fn split(&mut self, key: &mut u64, newval: T, target_id: PageId) -> Self;
 
-    fn borrow(&mut self, parent: &mut InnerNode, parent_slot: usize, sibling: &mut Self, is_right: bool);
     fn merge(&mut self, sibling: &mut Self, parent_key: u64);
 
     fn find_slot(&self, key: u64) -> usize {
-        match self.keys().binary_search(&key) {
+        match scan(self.keys(), key) {
             Ok(i) => i,
             Err(i) => i,
         }
@@ -48,27 +102,127 @@ pub trait Node<T> : Sized {
     }
 }
 
+/// Borrowing a key from a sibling needs to promote/demote a separator
+/// through `parent`, which (unlike every other `Node` operation) is
+/// necessarily an `InnerNode` — kept as its own trait, parameterized by
+/// the monoid `M`, so `Node`'s other methods stay inferable purely from
+/// `Self` without needing `M` threaded through every call site.
+pub trait Borrow<T, M: Monoid> : Node<T> {
+    fn borrow(&mut self, parent: &mut InnerNode<M>, parent_slot: usize, sibling: &mut Self, is_right: bool);
+}
+
 #[repr(packed)]
-pub struct InnerNode {
+pub struct InnerNode<M: Monoid> {
     count_: u16,
+    txid: u64,
     keys: [u64; 255],
     children: [PageId; 256],
+    /// `subtree_counts[i]` is the number of leaf entries reachable under
+    /// `children[i]`, kept in lockstep with `children` by every mutator
+    /// so `select`/`rank` can descend in O(log n) without visiting leaves.
+    subtree_counts: [u64; 256],
+    /// `summaries[i]` is `M`'s fold of every value reachable under
+    /// `children[i]`, kept in lockstep with `children` by every mutator
+    /// so `fold` can skip whole covered subtrees.
+    summaries: [M::Summary; 256],
+    checksum: u64,
 }
 
-impl InnerNode {
-    pub fn new(init_prev: PageId) -> InnerNode {
-        let mut node: InnerNode = unsafe { mem::uninitialized() };
+impl<M: Monoid> InnerNode<M> {
+    pub fn new(init_prev: PageId) -> InnerNode<M> {
+        let mut node: InnerNode<M> = unsafe { mem::uninitialized() };
         node.count_ = 0;
+        node.txid = 0;
         node.children[0] = init_prev;
+        node.subtree_counts[0] = 0;
+        node.summaries[0] = M::identity();
+        node.recompute_checksum();
         node
     }
 
     pub fn traverse(&self, key: u64) -> PageId {
         self.content()[self.find_slot(key)]
     }
+
+    /// Number of leaf entries reachable under `children[i]`.
+    pub fn child_count(&self, i: usize) -> u64 {
+        self.subtree_counts[i]
+    }
+
+    /// Sets the number of leaf entries reachable under `children[i]`.
+    pub fn set_child_count(&mut self, i: usize, count: u64) {
+        self.subtree_counts[i] = count;
+    }
+
+    /// Total number of leaf entries reachable from this node.
+    pub fn total_count(&self) -> u64 {
+        self.subtree_counts[..self.count() + 1].iter().sum()
+    }
+
+    /// `M`'s fold of every value reachable under `children[i]`.
+    pub fn child_summary(&self, i: usize) -> M::Summary {
+        self.summaries[i]
+    }
+
+    /// Sets `M`'s fold of every value reachable under `children[i]`.
+    pub fn set_child_summary(&mut self, i: usize, summary: M::Summary) {
+        self.summaries[i] = summary;
+    }
+
+    /// `M`'s fold of every value reachable from this node.
+    pub fn total_summary(&self) -> M::Summary {
+        let mut acc = M::identity();
+        for i in 0..self.count() + 1 {
+            acc = M::combine(acc, self.summaries[i]);
+        }
+        acc
+    }
+
+    /// Drops `children[0]` (and `keys[0]`) outright, keeping
+    /// `children[1..]` in place. `remove_idx` always keeps the lower of
+    /// a `(key, child)` pair and drops the upper one, which can't express
+    /// discarding the very first child since there is no lower neighbor
+    /// to keep instead — `remove_range` needs exactly that when a bulk
+    /// deletion covers a node's leftmost children.
+    pub fn remove_first(&mut self) -> PageId {
+        let ret = self.children[0];
+        unsafe {
+            ptr::copy(&self.keys[1], self.keys.as_mut_ptr(), self.count() - 1);
+            ptr::copy(&self.children[1], self.children.as_mut_ptr(), self.count());
+            ptr::copy(&self.subtree_counts[1], self.subtree_counts.as_mut_ptr(), self.count());
+            ptr::copy(&self.summaries[1], self.summaries.as_mut_ptr(), self.count());
+        }
+        self.count_ -= 1;
+        self.recompute_checksum();
+        ret
+    }
+
+    /// A shallow copy of this page stamped with a new transaction id,
+    /// used to root a copy-on-write write transaction at `new_txid`.
+    pub fn cow(&self, new_txid: u64) -> InnerNode<M> {
+        let mut copy: InnerNode<M> = unsafe { ptr::read(self) };
+        copy.txid = new_txid;
+        copy.recompute_checksum();
+        copy
+    }
+
+    fn compute_checksum(&self) -> u64 {
+        let count_ = self.count_;
+        let txid = self.txid;
+        unsafe {
+            checksum::hash_parts(&[
+                slice::from_raw_parts(&count_ as *const u16 as *const u8, mem::size_of::<u16>()),
+                slice::from_raw_parts(&txid as *const u64 as *const u8, mem::size_of::<u64>()),
+                slice::from_raw_parts(self.keys.as_ptr() as *const u8, self.count() * mem::size_of::<u64>()),
+                slice::from_raw_parts(self.children.as_ptr() as *const u8, (self.count() + 1) * mem::size_of::<PageId>()),
+                slice::from_raw_parts(self.subtree_counts.as_ptr() as *const u8, (self.count() + 1) * mem::size_of::<u64>()),
+                slice::from_raw_parts(self.summaries.as_ptr() as *const u8, (self.count() + 1) * mem::size_of::<M::Summary>()),
+            ])
+        }
+    }
 }
 
-impl Node<PageId> for InnerNode {
+impl<M: Monoid> Node<PageId> for InnerNode<M> {
     #[cfg(test)]
     fn debug(&self) {
         println!("Inner n={} {:?} {:?}", self.count(), self.keys(), self.content());
@@ -90,16 +244,38 @@ impl Node<PageId> for InnerNode {
         self.count_ as usize
     }
 
+    fn recompute_checksum(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    fn checksum_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+
+    fn txid(&self) -> u64 {
+        self.txid
+    }
+
+    fn set_txid(&mut self, txid: u64) {
+        self.txid = txid;
+        self.recompute_checksum();
+    }
+
     fn insert_idx(&mut self, i: usize, key: u64, newpage: PageId) {
         assert!(!self.full());
 
         unsafe {
             ptr::copy(&self.keys[i], self.keys.as_mut_ptr().offset(i as isize + 1), self.count() - i);
             ptr::copy(&self.children[i + 1], self.children.as_mut_ptr().offset(i as isize + 2), self.count() - i);
+            ptr::copy(&self.subtree_counts[i + 1], self.subtree_counts.as_mut_ptr().offset(i as isize + 2), self.count() - i);
+            ptr::copy(&self.summaries[i + 1], self.summaries.as_mut_ptr().offset(i as isize + 2), self.count() - i);
         }
         self.keys[i] = key;
         self.children[i + 1] = newpage;
+        self.subtree_counts[i + 1] = 0;
+        self.summaries[i + 1] = M::identity();
         self.count_ += 1;
+        self.recompute_checksum();
     }
 
     fn remove(&mut self, key: u64) -> Option<u64> {
@@ -121,17 +297,20 @@ impl Node<PageId> for InnerNode {
         unsafe {
             ptr::copy(&self.keys[i + 1], &mut self.keys[i], self.count() - i);
             ptr::copy(&self.children[i + 2], &mut self.children[i + 1], self.count() - i);
+            ptr::copy(&self.subtree_counts[i + 2], &mut self.subtree_counts[i + 1], self.count() - i);
+            ptr::copy(&self.summaries[i + 2], &mut self.summaries[i + 1], self.count() - i);
         }
         self.count_ -= 1;
+        self.recompute_checksum();
 
         ret
     }
 
-    fn split(&mut self, key: &mut u64, newval: PageId, _: PageId) -> InnerNode {
+    fn split(&mut self, key: &mut u64, newval: PageId, _: PageId) -> InnerNode<M> {
         debug_assert!(self.full());
 
         let newkey = *key;
-        let mut target: InnerNode = unsafe { mem::uninitialized() };
+        let mut target: InnerNode<M> = unsafe { mem::uninitialized() };
 
         let mut remain = (self.count() + 1) / 2;
         let mut rest = self.count() - remain;
@@ -144,15 +323,21 @@ impl Node<PageId> for InnerNode {
             let before = i - remain;
             target.keys[..before].copy_from_slice(&self.keys[remain..i]);
             target.children[..before+1].copy_from_slice(&self.children[remain..i+1]);
+            target.subtree_counts[..before+1].copy_from_slice(&self.subtree_counts[remain..i+1]);
+            target.summaries[..before+1].copy_from_slice(&self.summaries[remain..i+1]);
 
 
             target.keys[before] = newkey;
             target.children[before+1] = newval;
+            target.subtree_counts[before+1] = 0;
+            target.summaries[before+1] = M::identity();
 
             // count - i - 1
             let after = before + 1;
             target.keys[after..rest+1].copy_from_slice(&self.keys()[i..]);
             target.children[after+1..rest+2].copy_from_slice(&self.content()[i+1..]);
+            target.subtree_counts[after+1..rest+2].copy_from_slice(&self.subtree_counts[i+1..]);
+            target.summaries[after+1..rest+2].copy_from_slice(&self.summaries[i+1..]);
 
             remain -= 1;
             rest += 1;
@@ -161,13 +346,19 @@ impl Node<PageId> for InnerNode {
             // add to self
             target.keys[..rest-1].copy_from_slice(&self.keys()[remain+1..]);
             target.children[..rest].copy_from_slice(&self.content()[remain+1..]);
+            target.subtree_counts[..rest].copy_from_slice(&self.subtree_counts[remain+1..]);
+            target.summaries[..rest].copy_from_slice(&self.summaries[remain+1..]);
 
             unsafe {
                 ptr::copy(&self.keys[i], self.keys.as_mut_ptr().offset(i as isize + 1), remain - i);
                 ptr::copy(&self.children[i + 1], self.children.as_mut_ptr().offset(i as isize + 2), remain - i);
+                ptr::copy(&self.subtree_counts[i + 1], self.subtree_counts.as_mut_ptr().offset(i as isize + 2), remain - i);
+                ptr::copy(&self.summaries[i + 1], self.summaries.as_mut_ptr().offset(i as isize + 2), remain - i);
             }
             self.keys[i] = newkey;
             self.children[i + 1] = newval;
+            self.subtree_counts[i + 1] = 0;
+            self.summaries[i + 1] = M::identity();
             remain += 1;
             rest -= 1;
         }
@@ -175,11 +366,37 @@ impl Node<PageId> for InnerNode {
         self.count_ = remain as u16;
         target.count_ = rest as u16;
 
+        self.recompute_checksum();
+        target.recompute_checksum();
+
         target
     }
 
-    fn borrow(&mut self, parent: &mut InnerNode, parent_slot: usize,
-              sibling: &mut InnerNode, is_right: bool) {
+    fn merge(&mut self, sibling: &mut InnerNode<M>, parent_key: u64) {
+        assert!(self.count() + sibling.count() + 1 <= self.keys.len());
+        assert!(self.keys[0] < sibling.keys[0]);
+
+        let count = self.count();
+        self.keys[count+1..][..sibling.count()].copy_from_slice(sibling.keys());
+        self.children[count+1..][..sibling.count()+1].copy_from_slice(sibling.content());
+        self.subtree_counts[count+1..][..sibling.count()+1].copy_from_slice(&sibling.subtree_counts[..sibling.count()+1]);
+        self.summaries[count+1..][..sibling.count()+1].copy_from_slice(&sibling.summaries[..sibling.count()+1]);
+        self.keys[count] = parent_key;
+        self.count_ += sibling.count_ + 1;
+        self.recompute_checksum();
+    }
+
+    fn find_slot(&self, key: u64) -> usize {
+        match scan(self.keys(), key) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+}
+
+impl<M: Monoid> Borrow<PageId, M> for InnerNode<M> {
+    fn borrow(&mut self, parent: &mut InnerNode<M>, parent_slot: usize,
+              sibling: &mut InnerNode<M>, is_right: bool) {
         assert!(self.half_full());
         assert!(!sibling.half_full());
 
@@ -189,102 +406,177 @@ impl Node<PageId> for InnerNode {
             (sibling.count() - 1, 0)
         };
 
+        // Captured before `remove_idx` shifts `subtree_counts`/`summaries`
+        // out from under us: index `i_del + 1` is paired with the child
+        // `remove_idx` is about to return as `v`, not whatever ends up at
+        // index 0 afterwards (which only happens to coincide with it when
+        // `i_del == 0`, and even then only for `v`/`k`, not the count).
+        let mut c = sibling.subtree_counts[i_del + 1];
+        let mut s = sibling.summaries[i_del + 1];
         let (mut k, mut v) = sibling.remove_idx(i_del);
         if is_right {
             mem::swap(&mut k, &mut parent.keys[parent_slot]);
             mem::swap(&mut v, &mut sibling.children[0]);
+            mem::swap(&mut c, &mut sibling.subtree_counts[0]);
+            mem::swap(&mut s, &mut sibling.summaries[0]);
         } else {
             mem::swap(&mut k, &mut parent.keys[parent_slot - 1]);
             mem::swap(&mut v, &mut self.children[0]);
+            mem::swap(&mut c, &mut self.subtree_counts[0]);
+            mem::swap(&mut s, &mut self.summaries[0]);
         }
         self.insert_idx(i_ins, k, v);
+        self.subtree_counts[i_ins + 1] = c;
+        self.summaries[i_ins + 1] = s;
+        self.recompute_checksum();
+        sibling.recompute_checksum();
+        parent.recompute_checksum();
     }
+}
 
-    fn merge(&mut self, sibling: &mut InnerNode, parent_key: u64) {
-        assert!(self.count() + sibling.count() + 1 <= self.keys.len());
-        assert!(self.keys[0] < sibling.keys[0]);
 
-        let count = self.count();
-        self.keys[count+1..][..sibling.count()].copy_from_slice(sibling.keys());
-        self.children[count+1..][..sibling.count()+1].copy_from_slice(sibling.content());
-        self.keys[count] = parent_key;
-        self.count_ += sibling.count_ + 1;
-    }
 
-    fn find_slot(&self, key: u64) -> usize {
-        match self.keys().binary_search(&key) {
-            Ok(i) => i + 1,
-            Err(i) => i,
-        }
-    }
-}
+/// `value_tag[i]` of `OVERFLOW_TAG` marks a slot whose value didn't fit
+/// inline: `data[i]` holds its total byte length and `overflow[i]` the
+/// head page of its overflow chain. Any other tag is the number of
+/// live low-order bytes (little-endian) in `data[i]` itself — entries
+/// written through the plain `u64` API always use `U64_TAG` (all 8
+/// bytes); a shorter tag is an inline byte string written through
+/// `insert_bytes`.
+const OVERFLOW_TAG: u8 = 0xff;
+const U64_TAG: u8 = 8;
 
+fn le_bytes(value: u64, len: u8) -> Vec<u8> {
+    (0..len).map(|i| (value >> (8 * i as u32)) as u8).collect()
+}
 
+fn from_le_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().enumerate().fold(0u64, |acc, (i, &b)| acc | (b as u64) << (8 * i as u32))
+}
 
 #[repr(packed)]
 pub struct LeafNode {
     count_: u16,
+    txid: u64,
     keys: [u64; 255],
     data: [u64; 255],
+    value_tag: [u8; 255],
+    overflow: [PageId; 255],
     next: PageId,
+    checksum: u64,
 }
 
 impl LeafNode {
+    pub fn new() -> LeafNode {
+        let mut node: LeafNode = unsafe { mem::uninitialized() };
+        node.count_ = 0;
+        node.txid = 0;
+        node.next = PageId::null();
+        node.recompute_checksum();
+        node
+    }
+
+    /// `None` both when `key` is absent and when it was written through
+    /// `insert_bytes` with a value over 8 bytes — `data[i]` holds that
+    /// value's overflow-chain *length* rather than a `u64`, and there's
+    /// no sane `u64` to hand back, so this reads as "not a u64 value"
+    /// rather than returning that length as if it were one. Use
+    /// `get_bytes` for a key that might have been written either way.
     pub fn get(&self, key: u64) -> Option<u64> {
-        self.keys().binary_search(&key).ok().map(|i| self.data[i])
+        let i = self.keys().binary_search(&key).ok()?;
+        if self.is_overflow(i) {
+            None
+        } else {
+            Some(self.data[i])
+        }
     }
-}
 
-impl Node<u64> for LeafNode {
-    #[cfg(test)]
-    fn debug(&self) {
-        println!("Leaf n={} {:?} {:?} next={}", self.count(), self.keys(), self.content(), self.next);
+    /// The tag written to slot `i` by whichever of `insert`/`insert_bytes`
+    /// last wrote it — see `OVERFLOW_TAG`'s doc comment.
+    pub fn value_tag(&self, i: usize) -> u8 {
+        self.value_tag[i]
     }
 
+    /// Whether slot `i`'s value lives in an overflow chain rather than
+    /// inline.
+    pub fn is_overflow(&self, i: usize) -> bool {
+        self.value_tag[i] == OVERFLOW_TAG
+    }
 
-    fn keys(&self) -> &[u64] {
-        &self.keys[..self.count()]
+    /// Slot `i`'s literal inline bytes; meaningless when `value_tag(i) ==
+    /// OVERFLOW_TAG`; use `overflow_head`/`overflow_len` instead.
+    pub fn inline_bytes(&self, i: usize) -> Vec<u8> {
+        le_bytes(self.data[i], self.value_tag[i])
     }
 
-    fn content(&self) -> &[u64] {
-        &self.data[..self.count()]
+    /// Head page of slot `i`'s overflow chain; only meaningful when
+    /// `value_tag(i) == OVERFLOW_TAG`.
+    pub fn overflow_head(&self, i: usize) -> PageId {
+        self.overflow[i]
     }
 
-    fn content_mut(&mut self) -> &mut [PageId] {
-        &mut self.data[.. self.count_ as usize]
+    /// Slot `i`'s total value length; only meaningful when `value_tag(i)
+    /// == OVERFLOW_TAG` (otherwise the length is simply `value_tag(i)`).
+    pub fn overflow_len(&self, i: usize) -> u64 {
+        self.data[i]
     }
 
-    fn count(&self) -> usize {
-        self.count_ as usize
+    /// Like `insert_idx`, but for a value that may be too large to
+    /// inline: `overflow` is `Some((head, len))` for a value already
+    /// written to an overflow chain, `None` to inline `bytes` directly
+    /// (`bytes.len()` must then be at most 8).
+    pub fn insert_bytes_idx(&mut self, i: usize, key: u64, bytes: &[u8], overflow: Option<(PageId, u64)>) {
+        match overflow {
+            Some((head, len)) => self.insert_idx_raw(i, key, len, OVERFLOW_TAG, head),
+            None => self.insert_idx_raw(i, key, from_le_bytes(bytes), bytes.len() as u8, PageId::null()),
+        }
     }
 
-    fn insert_idx(&mut self, i: usize, key: u64, val: u64) {
+    fn insert_idx_raw(&mut self, i: usize, key: u64, word: u64, tag: u8, overflow: PageId) {
         assert!(!self.full());
 
         unsafe {
             ptr::copy(&self.keys[i], self.keys.as_mut_ptr().offset(i as isize + 1), self.count() - i);
             ptr::copy(&self.data[i], self.data.as_mut_ptr().offset(i as isize + 1), self.count() - i);
+            ptr::copy(&self.value_tag[i], self.value_tag.as_mut_ptr().offset(i as isize + 1), self.count() - i);
+            ptr::copy(&self.overflow[i], self.overflow.as_mut_ptr().offset(i as isize + 1), self.count() - i);
         }
         self.keys[i] = key;
-        self.data[i] = val;
+        self.data[i] = word;
+        self.value_tag[i] = tag;
+        self.overflow[i] = overflow;
         self.count_ += 1;
+        self.recompute_checksum();
     }
 
-    fn remove_idx(&mut self, i: usize) -> (u64, u64) {
+    fn remove_idx_raw(&mut self, i: usize) -> (u64, u64, u8, PageId) {
         // assert!(!self.half_full());
 
-        let ret = (self.keys[i], self.data[i]);
+        let ret = (self.keys[i], self.data[i], self.value_tag[i], self.overflow[i]);
 
         unsafe {
             ptr::copy(&self.keys[i + 1], &mut self.keys[i], self.count() - i - 1);
             ptr::copy(&self.data[i + 1], &mut self.data[i], self.count() - i - 1);
+            ptr::copy(&self.value_tag[i + 1], &mut self.value_tag[i], self.count() - i - 1);
+            ptr::copy(&self.overflow[i + 1], &mut self.overflow[i], self.count() - i - 1);
         }
         self.count_ -= 1;
+        self.recompute_checksum();
 
         ret
     }
 
-    fn split(&mut self, key: &mut u64, newval: u64, target_id: PageId) -> LeafNode {
+    /// Like `split`, but for a value that may be too large to inline —
+    /// see `insert_bytes_idx`.
+    pub fn split_bytes(&mut self, key: &mut u64, bytes: &[u8], overflow: Option<(PageId, u64)>, target_id: PageId) -> LeafNode {
+        let (word, tag, head) = match overflow {
+            Some((head, len)) => (len, OVERFLOW_TAG, head),
+            None => (from_le_bytes(bytes), bytes.len() as u8, PageId::null()),
+        };
+        self.split_raw(key, word, tag, head, target_id)
+    }
+
+    fn split_raw(&mut self, key: &mut u64, newval: u64, newtag: u8, newoverflow: PageId, target_id: PageId) -> LeafNode {
         debug_assert!(self.full());
 
         let newkey = *key;
@@ -305,24 +597,36 @@ impl Node<u64> for LeafNode {
             let before = i - remain;
             target.keys[..before].copy_from_slice(&self.keys[remain..i]);
             target.data[..before].copy_from_slice(&self.data[remain..i]);
+            target.value_tag[..before].copy_from_slice(&self.value_tag[remain..i]);
+            target.overflow[..before].copy_from_slice(&self.overflow[remain..i]);
 
             target.keys[i - remain] = newkey;
             target.data[i - remain] = newval;
+            target.value_tag[i - remain] = newtag;
+            target.overflow[i - remain] = newoverflow;
 
             let after = i - remain + 1;
             target.keys[after..rest].copy_from_slice(&self.keys()[i..]);
             target.data[after..rest].copy_from_slice(&self.content()[i..]);
+            target.value_tag[after..rest].copy_from_slice(&self.value_tag[i..self.count()]);
+            target.overflow[after..rest].copy_from_slice(&self.overflow[i..self.count()]);
         } else {
             // add to self
             target.keys[..rest].copy_from_slice(&self.keys()[remain..]);
             target.data[..rest].copy_from_slice(&self.content()[remain..]);
+            target.value_tag[..rest].copy_from_slice(&self.value_tag[remain..self.count()]);
+            target.overflow[..rest].copy_from_slice(&self.overflow[remain..self.count()]);
 
             unsafe {
                 ptr::copy(&self.keys[i], &mut self.keys[i + 1], remain - i);
                 ptr::copy(&self.data[i], &mut self.data[i + 1], remain - i);
+                ptr::copy(&self.value_tag[i], &mut self.value_tag[i + 1], remain - i);
+                ptr::copy(&self.overflow[i], &mut self.overflow[i + 1], remain - i);
             }
             self.keys[i] = newkey;
             self.data[i] = newval;
+            self.value_tag[i] = newtag;
+            self.overflow[i] = newoverflow;
 
             remain += 1;
         }
@@ -330,28 +634,169 @@ impl Node<u64> for LeafNode {
         self.count_ = remain as u16;
         target.count_ = rest as u16;
 
+        self.recompute_checksum();
+        target.recompute_checksum();
+
         *key = target.keys[0];
         target
     }
 
-    fn borrow(&mut self, parent: &mut InnerNode, parent_slot: usize,
-              sibling: &mut LeafNode, is_right: bool) {
-        assert!(self.half_full());
-        assert!(!sibling.half_full());
+    /// `M`'s fold over this leaf's values; leaves cache no summary of
+    /// their own, since folding a single page is already O(page size).
+    pub fn summary<M: Monoid>(&self) -> M::Summary {
+        let mut acc = M::identity();
+        for &v in self.content() {
+            acc = M::combine(acc, M::lift(v));
+        }
+        acc
+    }
 
-        let (i_del, i_ins) = if is_right {
-            (0, self.count())
-        } else {
-            (sibling.count() - 1, 0)
-        };
+    /// Removes every key in `[lo, hi)` with a single bulk shift instead
+    /// of one `remove_idx` call per key, returning how many entries were
+    /// removed.
+    pub fn remove_range(&mut self, lo: u64, hi: u64) -> usize {
+        let i = self.find_slot(lo);
+        let j = self.find_slot(hi);
+        let removed = j - i;
+        if removed == 0 {
+            return 0;
+        }
+        unsafe {
+            ptr::copy(&self.keys[j], &mut self.keys[i], self.count() - j);
+            ptr::copy(&self.data[j], &mut self.data[i], self.count() - j);
+            ptr::copy(&self.value_tag[j], &mut self.value_tag[i], self.count() - j);
+            ptr::copy(&self.overflow[j], &mut self.overflow[i], self.count() - j);
+        }
+        self.count_ -= removed as u16;
+        self.recompute_checksum();
+        removed
+    }
 
-        let (k, v) = sibling.remove_idx(i_del);
-        if is_right {
-            parent.keys[parent_slot /*+ 1*/] = sibling.keys[0];
+    /// Splits off every entry with `key' >= key` into a freshly
+    /// returned leaf, severing this leaf's `next` chain at the cut
+    /// (the returned leaf inherits it instead) since the two halves
+    /// become independent trees rather than neighbors in one chain.
+    pub fn split_off(&mut self, key: u64) -> LeafNode {
+        let i = self.find_slot(key);
+        let mut target: LeafNode = unsafe { mem::uninitialized() };
+
+        let rest = self.count() - i;
+        target.keys[..rest].copy_from_slice(&self.keys()[i..]);
+        target.data[..rest].copy_from_slice(&self.content()[i..]);
+        target.value_tag[..rest].copy_from_slice(&self.value_tag[i..self.count()]);
+        target.overflow[..rest].copy_from_slice(&self.overflow[i..self.count()]);
+        target.count_ = rest as u16;
+        target.next = self.next;
+
+        self.next = PageId::null();
+        self.count_ = i as u16;
+
+        self.recompute_checksum();
+        target.recompute_checksum();
+        target
+    }
+
+    /// The next leaf in key order, following the singly-linked chain
+    /// maintained across `split` and `merge`, or `None` at the last leaf.
+    pub fn next_leaf(&self) -> Option<PageId> {
+        if self.next == PageId::null() {
+            None
         } else {
-            parent.keys[parent_slot - 1] = k;
+            Some(self.next)
         }
-        self.insert_idx(i_ins, k, v);
+    }
+
+    /// A shallow copy of this page stamped with a new transaction id,
+    /// used by the copy-on-write write path.
+    pub fn cow(&self, new_txid: u64) -> LeafNode {
+        let mut copy: LeafNode = unsafe { ptr::read(self) };
+        copy.txid = new_txid;
+        copy.recompute_checksum();
+        copy
+    }
+
+    fn compute_checksum(&self) -> u64 {
+        let count_ = self.count_;
+        let txid = self.txid;
+        let next = self.next;
+        unsafe {
+            checksum::hash_parts(&[
+                slice::from_raw_parts(&count_ as *const u16 as *const u8, mem::size_of::<u16>()),
+                slice::from_raw_parts(&txid as *const u64 as *const u8, mem::size_of::<u64>()),
+                slice::from_raw_parts(self.keys.as_ptr() as *const u8, self.count() * mem::size_of::<u64>()),
+                slice::from_raw_parts(self.data.as_ptr() as *const u8, self.count() * mem::size_of::<u64>()),
+                slice::from_raw_parts(self.value_tag.as_ptr() as *const u8, self.count() * mem::size_of::<u8>()),
+                slice::from_raw_parts(self.overflow.as_ptr() as *const u8, self.count() * mem::size_of::<PageId>()),
+                slice::from_raw_parts(&next as *const PageId as *const u8, mem::size_of::<PageId>()),
+            ])
+        }
+    }
+}
+
+impl Node<u64> for LeafNode {
+    #[cfg(test)]
+    fn debug(&self) {
+        println!("Leaf n={} {:?} {:?} next={}", self.count(), self.keys(), self.content(), self.next);
+    }
+
+
+    fn keys(&self) -> &[u64] {
+        &self.keys[..self.count()]
+    }
+
+    fn content(&self) -> &[u64] {
+        &self.data[..self.count()]
+    }
+
+    fn content_mut(&mut self) -> &mut [u64] {
+        &mut self.data[.. self.count_ as usize]
+    }
+
+    fn count(&self) -> usize {
+        self.count_ as usize
+    }
+
+    fn recompute_checksum(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    fn checksum_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+
+    fn txid(&self) -> u64 {
+        self.txid
+    }
+
+    fn set_txid(&mut self, txid: u64) {
+        self.txid = txid;
+        self.recompute_checksum();
+    }
+
+    fn insert_idx(&mut self, i: usize, key: u64, val: u64) {
+        self.insert_idx_raw(i, key, val, U64_TAG, PageId::null());
+    }
+
+    fn remove_idx(&mut self, i: usize) -> (u64, u64) {
+        let (key, word, _tag, _overflow) = self.remove_idx_raw(i);
+        (key, word)
+    }
+
+    /// Overridden so a key removed via the plain `u64` API never hands
+    /// back an overflow chain's byte length disguised as a value — see
+    /// `get`'s doc comment. The entry is still removed either way.
+    fn remove(&mut self, key: u64) -> Option<u64> {
+        let i = self.find_slot(key);
+        if self.keys().get(i) != Some(&key) {
+            return None;
+        }
+        let overflow = self.is_overflow(i);
+        let (_, word, _, _) = self.remove_idx_raw(i);
+        if overflow { None } else { Some(word) }
+    }
+
+    fn split(&mut self, key: &mut u64, newval: u64, target_id: PageId) -> LeafNode {
+        self.split_raw(key, newval, U64_TAG, PageId::null(), target_id)
     }
 
     fn merge(&mut self, sibling: &mut LeafNode, _parent_key: u64) {
@@ -364,7 +809,59 @@ impl Node<u64> for LeafNode {
         let count = self.count();
         self.keys[count..][..sibling.count()].copy_from_slice(sibling.keys());
         self.data[count..][..sibling.count()].copy_from_slice(sibling.content());
+        self.value_tag[count..][..sibling.count()].copy_from_slice(&sibling.value_tag[..sibling.count()]);
+        self.overflow[count..][..sibling.count()].copy_from_slice(&sibling.overflow[..sibling.count()]);
         self.count_ += sibling.count_;
         self.next = sibling.next;
+        self.recompute_checksum();
+    }
+}
+
+impl<M: Monoid> Borrow<u64, M> for LeafNode {
+    fn borrow(&mut self, parent: &mut InnerNode<M>, parent_slot: usize,
+              sibling: &mut LeafNode, is_right: bool) {
+        assert!(self.half_full());
+        assert!(!sibling.half_full());
+
+        let (i_del, i_ins) = if is_right {
+            (0, self.count())
+        } else {
+            (sibling.count() - 1, 0)
+        };
+
+        let (k, v, tag, overflow) = sibling.remove_idx_raw(i_del);
+        if is_right {
+            parent.keys[parent_slot /*+ 1*/] = sibling.keys[0];
+        } else {
+            parent.keys[parent_slot - 1] = k;
+        }
+        self.insert_idx_raw(i_ins, k, v, tag, overflow);
+        parent.recompute_checksum();
+    }
+}
+
+#[cfg(all(test, feature = "simd_support"))]
+mod simd_tests {
+    extern crate rand;
+
+    use super::scan;
+    use self::rand::Rng;
+
+    #[test]
+    fn agrees_with_scalar_binary_search_over_random_fills() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let count = rng.gen_range(0, 256);
+            let mut keys: Vec<u64> = (0..count).map(|_| rng.gen_range(0, 1000)).collect();
+            keys.sort();
+            // Node keys are unique; with duplicates `binary_search` may
+            // return any of the equal indices.
+            keys.dedup();
+
+            for _ in 0..20 {
+                let target = rng.gen_range(0, 1000);
+                assert_eq!(scan(&keys, target), keys.binary_search(&target));
+            }
+        }
     }
 }
@@ -0,0 +1,65 @@
+use std::{mem, slice};
+use mappedheap::PageId;
+
+use super::checksum;
+
+/// How many payload bytes a single overflow page holds; byte values
+/// longer than this are chained across several pages via `next`.
+pub const CAPACITY: usize = 4096;
+
+/// One page of an overflow chain holding one chunk of a leaf value too
+/// large to inline. Unlike `LeafNode`/`InnerNode`, a chain is never
+/// mutated once written — `insert_bytes` always allocates a fresh chain
+/// rather than editing an existing one in place — so there's no `txid`
+/// or copy-on-write bookkeeping here, only a checksum for corruption
+/// detection.
+#[repr(packed)]
+pub struct OverflowPage {
+    len: u32,
+    next: PageId,
+    checksum: u64,
+    data: [u8; CAPACITY],
+}
+
+impl OverflowPage {
+    pub fn new(chunk: &[u8], next: PageId) -> OverflowPage {
+        let mut page: OverflowPage = unsafe { mem::uninitialized() };
+        page.data[..chunk.len()].copy_from_slice(chunk);
+        page.len = chunk.len() as u32;
+        page.next = next;
+        page.recompute_checksum();
+        page
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+
+    pub fn next_page(&self) -> Option<PageId> {
+        if self.next == PageId::null() {
+            None
+        } else {
+            Some(self.next)
+        }
+    }
+
+    fn compute_checksum(&self) -> u64 {
+        let len = self.len;
+        let next = self.next;
+        unsafe {
+            checksum::hash_parts(&[
+                slice::from_raw_parts(&len as *const u32 as *const u8, mem::size_of::<u32>()),
+                slice::from_raw_parts(&next as *const PageId as *const u8, mem::size_of::<PageId>()),
+                self.bytes(),
+            ])
+        }
+    }
+
+    pub fn recompute_checksum(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    pub fn checksum_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+}
@@ -1,5 +1,6 @@
 use std::ops::{Deref, DerefMut};
 pub use super::node::{InnerNode as InnerNodeActual, LeafNode as LeafNodeActual};
+pub use super::overflow::OverflowPage as OverflowPageActual;
 
 // no packed enums and no way to force lower alignment -> need ugly hacks
 #[repr(packed)]
@@ -26,5 +27,6 @@ impl<T> From<T> for Unalign<T> {
     }
 }
 
-pub type InnerNode = Unalign<InnerNodeActual>;
+pub type InnerNode<M> = Unalign<InnerNodeActual<M>>;
 pub type LeafNode = Unalign<LeafNodeActual>;
+pub type OverflowPage = Unalign<OverflowPageActual>;
@@ -1,5 +1,7 @@
 extern crate mappedheap;
 extern crate futex;
+#[cfg(feature = "simd_support")]
+extern crate packed_simd;
 #[cfg(test)]
 extern crate rand;
 
@@ -7,4 +9,5 @@ mod btree;
 mod ref_btree;
 
 pub use btree::MappedBTree as BTree;
+pub use btree::monoid::{Monoid, Max, Min, Sum};
 pub use ref_btree::RefBTree;
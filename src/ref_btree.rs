@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::ops::Bound::{Excluded, Included};
+
+/// A plain `BTreeMap`-backed stand-in for `MappedBTree`'s `u64` map
+/// operations (lookup, insert, range removal, split and iteration).
+/// Used as the oracle in randomized tests that check the
+/// on-disk tree against a trusted, in-memory implementation.
+pub struct RefBTree {
+    map: BTreeMap<u64, u64>,
+}
+
+impl RefBTree {
+    pub fn new() -> RefBTree {
+        RefBTree { map: BTreeMap::new() }
+    }
+
+    pub fn get(&self, key: u64) -> Option<u64> {
+        self.map.get(&key).cloned()
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        self.map.contains_key(&key)
+    }
+
+    pub fn insert(&mut self, key: u64, value: u64) {
+        self.map.insert(key, value);
+    }
+
+    /// Removes every entry with `lo <= key < hi`.
+    pub fn remove_range(&mut self, lo: u64, hi: u64) {
+        let keys: Vec<u64> = self.range(lo, hi).map(|(k, _)| k).collect();
+        for k in keys {
+            self.map.remove(&k);
+        }
+    }
+
+    /// Partitions the map at `key`, keeping `key' < key` in `self` and
+    /// returning every entry with `key' >= key` as a new map.
+    pub fn split_off(&mut self, key: u64) -> RefBTree {
+        RefBTree { map: self.map.split_off(&key) }
+    }
+
+    pub fn iter(&self) -> RefIter {
+        RefIter { inner: self.map.range(..) }
+    }
+
+    /// An iterator over `(key, value)` pairs with `lo <= key < hi`.
+    pub fn range(&self, lo: u64, hi: u64) -> RefIter {
+        RefIter { inner: self.map.range((Included(lo), Excluded(hi))) }
+    }
+}
+
+pub struct RefIter<'a> {
+    inner: btree_map::Range<'a, u64, u64>,
+}
+
+impl<'a> Iterator for RefIter<'a> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        self.inner.next().map(|(&k, &v)| (k, v))
+    }
+}